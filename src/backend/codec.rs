@@ -0,0 +1,240 @@
+//! SCALE-codec key-value backend adapter.
+//!
+//! Implements [`Backend`]/[`ApplyBackend`] over a user-supplied key-value store
+//! (e.g. a Substrate runtime's on-chain storage) by encoding account metadata,
+//! code and storage entries with [`codec::Encode`]/[`codec::Decode`] under a
+//! handful of deterministic key prefixes.
+
+use alloc::vec::Vec;
+use primitive_types::{H160, H256, U256};
+use codec::{Decode, Encode};
+use sha3::{Digest, Keccak256};
+use crate::{Capture, Context, ExitReason, Transfer};
+use crate::backend::{Apply, ApplyBackend, Backend, Basic, Log};
+use core::convert::Infallible;
+
+/// Prefix for account metadata, keyed by address.
+const PREFIX_ACCOUNT: u8 = 0x00;
+/// Prefix for contract code, keyed by code hash.
+const PREFIX_CODE: u8 = 0x01;
+/// Prefix for contract storage, keyed by address then index.
+const PREFIX_STORAGE: u8 = 0x02;
+
+/// Account metadata as persisted under [`PREFIX_ACCOUNT`].
+#[derive(Clone, Debug, Default, Encode, Decode)]
+struct AccountMetadata {
+	balance: U256,
+	nonce: U256,
+	code_hash: H256,
+}
+
+/// Abstract key-value store backing a [`CodecBackend`].
+pub trait KeyValueStore {
+	/// Read the value stored under `key`, if any.
+	fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+	/// Write `value` under `key`.
+	fn set(&mut self, key: &[u8], value: &[u8]);
+	/// Remove the value stored under `key`.
+	fn remove(&mut self, key: &[u8]);
+	/// Remove every entry whose key starts with `prefix`.
+	fn clear_prefix(&mut self, prefix: &[u8]);
+}
+
+/// `keccak256` of `data`.
+fn keccak256(data: &[u8]) -> H256 {
+	H256::from_slice(Keccak256::digest(data).as_slice())
+}
+
+fn account_key(address: H160) -> Vec<u8> {
+	let mut key = Vec::with_capacity(21);
+	key.push(PREFIX_ACCOUNT);
+	key.extend_from_slice(address.as_bytes());
+	key
+}
+
+fn code_key(code_hash: H256) -> Vec<u8> {
+	let mut key = Vec::with_capacity(33);
+	key.push(PREFIX_CODE);
+	key.extend_from_slice(code_hash.as_bytes());
+	key
+}
+
+fn storage_prefix(address: H160) -> Vec<u8> {
+	let mut key = Vec::with_capacity(21);
+	key.push(PREFIX_STORAGE);
+	key.extend_from_slice(address.as_bytes());
+	key
+}
+
+fn storage_key(address: H160, index: H256) -> Vec<u8> {
+	let mut key = storage_prefix(address);
+	key.extend_from_slice(index.as_bytes());
+	key
+}
+
+/// A [`Backend`] implemented over a SCALE-codec key-value `store`, carrying the
+/// environmental context in `vicinity`.
+pub struct CodecBackend<'vicinity, S> {
+	vicinity: &'vicinity CodecVicinity,
+	store: S,
+}
+
+/// Environmental context for a [`CodecBackend`].
+#[derive(Clone, Debug)]
+pub struct CodecVicinity {
+	pub gas_price: U256,
+	pub origin: H160,
+	pub chain_id: U256,
+	pub block_hashes: Vec<H256>,
+	pub block_number: U256,
+	pub block_coinbase: H160,
+	pub block_timestamp: U256,
+	pub block_difficulty: U256,
+	pub block_gas_limit: U256,
+	pub block_base_fee_per_gas: U256,
+}
+
+impl<'vicinity, S: KeyValueStore> CodecBackend<'vicinity, S> {
+	/// Wrap `store` with the environment described by `vicinity`.
+	pub fn new(vicinity: &'vicinity CodecVicinity, store: S) -> Self {
+		Self { vicinity, store }
+	}
+
+	/// Consume the adapter, returning the underlying store.
+	pub fn into_store(self) -> S {
+		self.store
+	}
+
+	fn metadata(&self, address: H160) -> AccountMetadata {
+		self.store.get(&account_key(address))
+			.and_then(|raw| AccountMetadata::decode(&mut raw.as_slice()).ok())
+			.unwrap_or_default()
+	}
+}
+
+impl<'vicinity, S: KeyValueStore> Backend for CodecBackend<'vicinity, S> {
+	type Error = Infallible;
+
+	fn gas_price(&self) -> U256 { self.vicinity.gas_price }
+	fn origin(&self) -> H160 { self.vicinity.origin }
+	fn block_number(&self) -> U256 { self.vicinity.block_number }
+	fn block_coinbase(&self) -> H160 { self.vicinity.block_coinbase }
+	fn block_timestamp(&self) -> U256 { self.vicinity.block_timestamp }
+	fn block_difficulty(&self) -> U256 { self.vicinity.block_difficulty }
+	fn block_gas_limit(&self) -> U256 { self.vicinity.block_gas_limit }
+	fn chain_id(&self) -> U256 { self.vicinity.chain_id }
+	fn block_base_fee_per_gas(&self) -> U256 { self.vicinity.block_base_fee_per_gas }
+
+	fn block_hash(&self, number: U256) -> Result<H256, Self::Error> {
+		if number >= self.vicinity.block_number ||
+			self.vicinity.block_number - number - U256::one()
+				>= U256::from(self.vicinity.block_hashes.len())
+		{
+			Ok(H256::default())
+		} else {
+			let index = (self.vicinity.block_number - number - U256::one()).as_usize();
+			Ok(self.vicinity.block_hashes[index])
+		}
+	}
+
+	fn exists(&self, address: H160) -> Result<bool, Self::Error> {
+		Ok(self.store.get(&account_key(address)).is_some())
+	}
+
+	fn basic(&self, address: H160) -> Result<Basic, Self::Error> {
+		let metadata = self.metadata(address);
+		Ok(Basic { balance: metadata.balance, nonce: metadata.nonce })
+	}
+
+	fn code_hash(&self, address: H160) -> Result<H256, Self::Error> {
+		// Derive the hash from the stored code so a present but code-less
+		// account reports `keccak256("")`, matching `MemoryBackend`, rather than
+		// the zero default carried in its metadata.
+		Ok(keccak256(&self.code(address)?))
+	}
+
+	fn code_size(&self, address: H160) -> Result<usize, Self::Error> {
+		Ok(self.code(address)?.len())
+	}
+
+	fn code(&self, address: H160) -> Result<Vec<u8>, Self::Error> {
+		let code_hash = self.metadata(address).code_hash;
+		Ok(self.store.get(&code_key(code_hash)).unwrap_or_default())
+	}
+
+	fn storage(&self, address: H160, index: H256) -> Result<H256, Self::Error> {
+		Ok(self.store.get(&storage_key(address, index))
+			.and_then(|raw| H256::decode(&mut raw.as_slice()).ok())
+			.unwrap_or_default())
+	}
+
+	fn handle_call(
+		&self,
+		_code_address: H160,
+		_transfer: Option<Transfer>,
+		_input: Vec<u8>,
+		_target_gas: Option<usize>,
+		_is_static: bool,
+		_take_l64: bool,
+		_take_stipend: bool,
+		_context: Context,
+	) -> Option<Capture<(ExitReason, Vec<u8>), Infallible>> {
+		None
+	}
+}
+
+impl<'vicinity, S: KeyValueStore> ApplyBackend for CodecBackend<'vicinity, S> {
+	fn apply<A, I, L>(
+		&mut self,
+		values: A,
+		_logs: L,
+		delete_empty: bool,
+	) where
+		A: IntoIterator<Item=Apply<I>>,
+		I: IntoIterator<Item=(H256, H256)>,
+		L: IntoIterator<Item=Log>,
+	{
+		for apply in values {
+			match apply {
+				Apply::Modify { address, basic, code, storage, reset_storage } => {
+					let mut metadata = self.metadata(address);
+					metadata.balance = basic.balance;
+					metadata.nonce = basic.nonce;
+
+					if let Some(code) = code {
+						let code_hash = keccak256(&code);
+						self.store.set(&code_key(code_hash), &code);
+						metadata.code_hash = code_hash;
+					}
+
+					if reset_storage {
+						self.store.clear_prefix(&storage_prefix(address));
+					}
+
+					for (index, value) in storage {
+						let key = storage_key(address, index);
+						if value == H256::default() {
+							self.store.remove(&key);
+						} else {
+							self.store.set(&key, &value.encode());
+						}
+					}
+
+					let is_empty = metadata.nonce.is_zero()
+						&& metadata.balance.is_zero()
+						&& (metadata.code_hash.is_zero() || metadata.code_hash == keccak256(&[]));
+					if is_empty && delete_empty {
+						self.store.remove(&account_key(address));
+						self.store.clear_prefix(&storage_prefix(address));
+					} else {
+						self.store.set(&account_key(address), &metadata.encode());
+					}
+				},
+				Apply::Delete { address } => {
+					self.store.remove(&account_key(address));
+					self.store.clear_prefix(&storage_prefix(address));
+				},
+			}
+		}
+	}
+}
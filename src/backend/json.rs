@@ -0,0 +1,206 @@
+//! # `GeneralStateTests` fixture runner
+//!
+//! Parses the standard `ethereum/tests` `GeneralStateTests`/`stateTest` JSON
+//! layout into a [`MemoryVicinity`] and a pre-state account map, runs the
+//! encoded transaction on a [`MemoryBackend`], and checks the mutated state
+//! against the fixture's expected `post` entries for a selected fork.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use primitive_types::{H160, H256, U256};
+use serde::Deserialize;
+use crate::{Config, ExitReason};
+use crate::backend::{ApplyBackend, MemoryAccount, MemoryBackend, MemoryVicinity};
+use crate::executor::StackExecutor;
+
+/// A single `stateTest` entry, keyed by test name in the fixture file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StateTest {
+	pub env: TestEnv,
+	pub pre: BTreeMap<H160, TestAccount>,
+	pub transaction: TestTransaction,
+	pub post: BTreeMap<String, Vec<TestPost>>,
+}
+
+/// The `env` block mapped onto the fields of a [`MemoryVicinity`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestEnv {
+	pub current_coinbase: H160,
+	pub current_difficulty: U256,
+	pub current_gas_limit: U256,
+	pub current_number: U256,
+	pub current_timestamp: U256,
+	#[serde(default)]
+	pub current_base_fee: U256,
+}
+
+/// A `pre` account, hydrated into a [`MemoryAccount`].
+#[derive(Clone, Debug, Deserialize)]
+pub struct TestAccount {
+	pub balance: U256,
+	pub nonce: U256,
+	#[serde(with = "crate::backend::json::hexbytes")]
+	pub code: Vec<u8>,
+	pub storage: BTreeMap<U256, U256>,
+}
+
+/// The encoded transaction. The test format supplies vectors of candidate
+/// values indexed by the `post` entry's `indexes`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestTransaction {
+	pub gas_price: U256,
+	pub nonce: U256,
+	#[serde(deserialize_with = "crate::backend::json::opt_address")]
+	pub to: Option<H160>,
+	pub value: Vec<U256>,
+	pub gas_limit: Vec<U256>,
+	#[serde(with = "crate::backend::json::hexbytes_seq")]
+	pub data: Vec<Vec<u8>>,
+}
+
+/// An expected post-state entry for one `(data, gas, value)` index triple.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TestPost {
+	pub hash: H256,
+	pub indexes: TestIndexes,
+}
+
+/// Indexes selecting which transaction candidate produced this post entry.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct TestIndexes {
+	pub data: usize,
+	pub gas: usize,
+	pub value: usize,
+}
+
+impl TestEnv {
+	/// Build the [`MemoryVicinity`] described by this `env` block. The origin
+	/// and gas price are taken from the transaction being executed.
+	pub fn vicinity(&self, origin: H160, gas_price: U256) -> MemoryVicinity {
+		MemoryVicinity {
+			gas_price,
+			origin,
+			chain_id: U256::one(),
+			block_hashes: Vec::new(),
+			block_number: self.current_number,
+			block_coinbase: self.current_coinbase,
+			block_timestamp: self.current_timestamp,
+			block_difficulty: self.current_difficulty,
+			block_gas_limit: self.current_gas_limit,
+			block_base_fee_per_gas: self.current_base_fee,
+		}
+	}
+}
+
+impl From<TestAccount> for MemoryAccount {
+	fn from(account: TestAccount) -> Self {
+		let storage = account.storage.into_iter()
+			.filter(|(_, v)| !v.is_zero())
+			.map(|(k, v)| (H256::from_uint(&k), H256::from_uint(&v)))
+			.collect();
+
+		MemoryAccount {
+			nonce: account.nonce,
+			balance: account.balance,
+			code: account.code,
+			storage,
+		}
+	}
+}
+
+impl StateTest {
+	/// Run this test against `config` for the given `fork`, returning whether
+	/// the resulting state root matches the fixture's expected `post` hash.
+	///
+	/// `origin` is the transaction sender recovered from the fixture's secret
+	/// key by the caller; the nonce increment, balance debit and gas payment all
+	/// land on this account, so passing the wrong address makes every check fail.
+	///
+	/// Returns `None` when the fixture carries no `post` entries for `fork`.
+	pub fn check(&self, config: &Config, fork: &str, origin: H160) -> Option<bool> {
+		let posts = self.post.get(fork)?;
+
+		Some(posts.iter().all(|post| {
+			let vicinity = self.env.vicinity(origin, self.transaction.gas_price);
+			let state = self.pre.iter()
+				.map(|(addr, acc)| (*addr, acc.clone().into()))
+				.collect();
+			let mut backend = MemoryBackend::new(&vicinity, state);
+
+			let gas_limit = self.transaction.gas_limit[post.indexes.gas];
+			let value = self.transaction.value[post.indexes.value];
+			let data = self.transaction.data[post.indexes.data].clone();
+
+			let mut executor = StackExecutor::new(&backend, gas_limit.as_usize(), config);
+			let reason = match self.transaction.to {
+				Some(to) => executor.transact_call(
+					origin, to, value, data, gas_limit.as_usize(),
+				).0,
+				None => executor.transact_create(
+					origin, value, data, gas_limit.as_usize(),
+				),
+			};
+
+			if let ExitReason::Fatal(_) = reason {
+				return false;
+			}
+
+			// The executor refunds the sender but does not pay the beneficiary;
+			// credit the transaction fee to the coinbase before settling so the
+			// resulting state root matches the fixture.
+			executor.deposit(vicinity.block_coinbase, executor.fee(self.transaction.gas_price));
+
+			let (values, logs) = executor.deconstruct();
+			// `delete_empty` is the inverse of `empty_considered_exists`: the
+			// EIP-161 forks leave empties unresolved and prune them here.
+			backend.apply(values, logs, !config.empty_considered_exists);
+			backend.state_root() == post.hash
+		}))
+	}
+}
+
+/// Deserialize the transaction `to` field, mapping the empty string used by
+/// contract-creation fixtures to `None`.
+pub fn opt_address<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<H160>, D::Error> {
+	let s = String::deserialize(d)?;
+	let s = s.strip_prefix("0x").unwrap_or(&s);
+	if s.is_empty() {
+		return Ok(None);
+	}
+	let bytes = (0..s.len()).step_by(2)
+		.map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(serde::de::Error::custom))
+		.collect::<Result<Vec<u8>, _>>()?;
+	Ok(Some(H160::from_slice(&bytes)))
+}
+
+mod hexbytes {
+	use alloc::vec::Vec;
+	use alloc::string::String;
+	use serde::{Deserialize, Deserializer};
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+		let s = String::deserialize(d)?;
+		let s = s.strip_prefix("0x").unwrap_or(&s);
+		(0..s.len()).step_by(2)
+			.map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(serde::de::Error::custom))
+			.collect()
+	}
+}
+
+mod hexbytes_seq {
+	use alloc::vec::Vec;
+	use alloc::string::String;
+	use serde::{Deserialize, Deserializer};
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Vec<u8>>, D::Error> {
+		Vec::<String>::deserialize(d)?.into_iter().map(|s| {
+			let s = s.strip_prefix("0x").unwrap_or(&s);
+			(0..s.len()).step_by(2)
+				.map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(serde::de::Error::custom))
+				.collect()
+		}).collect()
+	}
+}
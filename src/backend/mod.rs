@@ -2,9 +2,18 @@
 //!
 //! Backends store state information of the VM, and exposes it to runtime.
 
+#[cfg(feature = "with-codec")]
+mod codec;
 mod memory;
+mod overlay;
+mod root;
+#[cfg(feature = "json-tests")]
+pub mod json;
 
 pub use self::memory::{MemoryBackend, MemoryVicinity, MemoryAccount};
+pub use self::overlay::{Checkpoint, OverlayBackend};
+#[cfg(feature = "with-codec")]
+pub use self::codec::{CodecBackend, CodecVicinity, KeyValueStore};
 
 use alloc::vec::Vec;
 use primitive_types::{H160, H256, U256};
@@ -48,14 +57,51 @@ pub enum Apply<I> {
 	},
 }
 
+/// Bound on how much the base fee can change between two consecutive blocks,
+/// as defined by EIP-1559.
+pub const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+/// Divisor applied to a block's gas limit to obtain its gas target.
+pub const ELASTICITY_MULTIPLIER: u64 = 2;
+
+/// Derive the base fee per gas of the block following `parent`, applying the
+/// EIP-1559 rule to the parent's gas usage.
+///
+/// The parent's gas target is `parent_gas_limit / ELASTICITY_MULTIPLIER`. If
+/// the parent exactly hit its target the base fee is carried over unchanged;
+/// otherwise it moves towards the target, bounded by
+/// `BASE_FEE_MAX_CHANGE_DENOMINATOR`, with an upward move always at least `1`.
+pub fn calc_next_base_fee(
+	parent_gas_used: U256,
+	parent_gas_limit: U256,
+	parent_base_fee: U256,
+) -> U256 {
+	let gas_target = parent_gas_limit / ELASTICITY_MULTIPLIER;
+
+	if parent_gas_used == gas_target {
+		parent_base_fee
+	} else if parent_gas_used > gas_target {
+		let delta = parent_base_fee * (parent_gas_used - gas_target)
+			/ gas_target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+		parent_base_fee + core::cmp::max(delta, U256::one())
+	} else {
+		let delta = parent_base_fee * (gas_target - parent_gas_used)
+			/ gas_target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+		parent_base_fee.saturating_sub(delta)
+	}
+}
+
 /// EVM backend.
 pub trait Backend {
+	/// Error returned by state accessors when the underlying store fails, e.g.
+	/// a missing trie node or an I/O error on a remote state database.
+	type Error;
+
 	/// Gas price.
 	fn gas_price(&self) -> U256;
 	/// Origin.
 	fn origin(&self) -> H160;
 	/// Environmental block hash.
-	fn block_hash(&self, number: U256) -> H256;
+	fn block_hash(&self, number: U256) -> Result<H256, Self::Error>;
 	/// Environmental block number.
 	fn block_number(&self) -> U256;
 	/// Environmental coinbase.
@@ -68,19 +114,21 @@ pub trait Backend {
 	fn block_gas_limit(&self) -> U256;
 	/// Environmental chain ID.
 	fn chain_id(&self) -> U256;
+	/// Environmental block base fee per gas (EIP-1559).
+	fn block_base_fee_per_gas(&self) -> U256;
 
 	/// Whether account at address exists.
-	fn exists(&self, address: H160) -> bool;
+	fn exists(&self, address: H160) -> Result<bool, Self::Error>;
 	/// Get basic account information.
-	fn basic(&self, address: H160) -> Basic;
+	fn basic(&self, address: H160) -> Result<Basic, Self::Error>;
 	/// Get account code hash.
-	fn code_hash(&self, address: H160) -> H256;
+	fn code_hash(&self, address: H160) -> Result<H256, Self::Error>;
 	/// Get account code size.
-	fn code_size(&self, address: H160) -> usize;
+	fn code_size(&self, address: H160) -> Result<usize, Self::Error>;
 	/// Get account code.
-	fn code(&self, address: H160) -> Vec<u8>;
+	fn code(&self, address: H160) -> Result<Vec<u8>, Self::Error>;
 	/// Get storage value of address at index.
-	fn storage(&self, address: H160, index: H256) -> H256;
+	fn storage(&self, address: H160, index: H256) -> Result<H256, Self::Error>;
 
 	/// Handles call if it is external
 	fn handle_call(
@@ -0,0 +1,318 @@
+//! Journaled overlay backend.
+//!
+//! [`OverlayBackend`] wraps any [`Backend`] and buffers mutations in a stack of
+//! journal layers so that nested call frames can be rolled back without copying
+//! the whole state. Reads consult the topmost layer holding a key and fall back
+//! to the wrapped backend; on settlement the accumulated changes are emitted as
+//! an [`Apply`] iterator suitable for [`ApplyBackend::apply`].
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use primitive_types::{H160, H256, U256};
+use crate::{Capture, Context, ExitReason, Transfer};
+use crate::backend::{Apply, Backend, Basic, Log};
+use core::convert::Infallible;
+
+/// Handle identifying a journal layer, returned by [`OverlayBackend::checkpoint`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Checkpoint(usize);
+
+/// A single journal layer recording the mutations made since the checkpoint
+/// that opened it.
+#[derive(Clone, Debug, Default)]
+struct Layer {
+	basics: BTreeMap<H160, Basic>,
+	codes: BTreeMap<H160, Vec<u8>>,
+	storage: BTreeMap<(H160, H256), H256>,
+	reset_storage: BTreeSet<H160>,
+	deleted: BTreeSet<H160>,
+	logs: Vec<Log>,
+}
+
+impl Layer {
+	/// Fold `other`, which sits directly above this layer, into `self`.
+	fn absorb(&mut self, other: Layer) {
+		// A deletion recorded in the upper layer supersedes any write buffered
+		// below it for that address.
+		for address in &other.deleted {
+			self.basics.remove(address);
+			self.codes.remove(address);
+			self.storage.retain(|(addr, _), _| addr != address);
+			self.reset_storage.remove(address);
+		}
+		// A reset recorded in the upper layer wipes any slot buffered below it,
+		// so drop `self`'s writes for those addresses before layering `other`'s
+		// post-reset writes back on top.
+		for address in &other.reset_storage {
+			self.storage.retain(|(addr, _), _| addr != address);
+		}
+		// A basic/code write in the upper layer resurrects an account the lower
+		// layer deleted, clearing the stale deletion.
+		for address in other.basics.keys().chain(other.codes.keys()) {
+			self.deleted.remove(address);
+		}
+		self.basics.extend(other.basics);
+		self.codes.extend(other.codes);
+		self.reset_storage.extend(other.reset_storage);
+		self.storage.extend(other.storage);
+		self.deleted.extend(other.deleted);
+		self.logs.extend(other.logs);
+	}
+}
+
+/// A [`Backend`] overlay buffering writes in a rollback-able journal.
+pub struct OverlayBackend<'backend, B> {
+	backend: &'backend B,
+	layers: Vec<Layer>,
+}
+
+impl<'backend, B: Backend> OverlayBackend<'backend, B> {
+	/// Create an overlay over `backend` with a single base layer.
+	pub fn new(backend: &'backend B) -> Self {
+		Self { backend, layers: alloc::vec![Layer::default()] }
+	}
+
+	/// Open a new journal layer and return a handle to it.
+	pub fn checkpoint(&mut self) -> Checkpoint {
+		self.layers.push(Layer::default());
+		Checkpoint(self.layers.len() - 1)
+	}
+
+	/// Discard every mutation made since `handle` was taken.
+	pub fn revert(&mut self, handle: Checkpoint) {
+		self.layers.truncate(handle.0);
+		if self.layers.is_empty() {
+			self.layers.push(Layer::default());
+		}
+	}
+
+	/// Fold the layer opened by `handle`, and any above it, into its parent.
+	pub fn commit(&mut self, handle: Checkpoint) {
+		while self.layers.len() > handle.0 {
+			let layer = self.layers.pop().expect("layer count checked by loop guard; qed");
+			match self.layers.last_mut() {
+				Some(parent) => parent.absorb(layer),
+				None => self.layers.push(layer),
+			}
+		}
+	}
+
+	/// Find the topmost layer, if any, recording `key`.
+	fn top<'a, T>(&'a self, key: impl Fn(&'a Layer) -> Option<T>) -> Option<T> {
+		self.layers.iter().rev().find_map(key)
+	}
+
+	/// Whether `address` is currently deleted, honouring resurrection order: a
+	/// basic/code write in a higher layer clears a deletion recorded below it.
+	fn is_deleted(&self, address: H160) -> bool {
+		for layer in self.layers.iter().rev() {
+			if layer.basics.contains_key(&address) || layer.codes.contains_key(&address) {
+				return false;
+			}
+			if layer.deleted.contains(&address) {
+				return true;
+			}
+		}
+		false
+	}
+
+	/// Record a balance/nonce write in the topmost layer.
+	pub fn set_basic(&mut self, address: H160, basic: Basic) {
+		let layer = self.layers.last_mut().expect("overlay always has a layer; qed");
+		// A write resurrects an account deleted earlier in this same layer.
+		layer.deleted.remove(&address);
+		layer.basics.insert(address, basic);
+	}
+
+	/// Record a code write in the topmost layer.
+	pub fn set_code(&mut self, address: H160, code: Vec<u8>) {
+		let layer = self.layers.last_mut().expect("overlay always has a layer; qed");
+		// A write resurrects an account deleted earlier in this same layer.
+		layer.deleted.remove(&address);
+		layer.codes.insert(address, code);
+	}
+
+	/// Record a storage write in the topmost layer.
+	pub fn set_storage(&mut self, address: H160, index: H256, value: H256) {
+		self.layers.last_mut().expect("overlay always has a layer; qed")
+			.storage.insert((address, index), value);
+	}
+
+	/// Record that `address`'s storage should be wiped on settlement.
+	pub fn reset_storage(&mut self, address: H160) {
+		let layer = self.layers.last_mut().expect("overlay always has a layer; qed");
+		// The reset supersedes writes buffered earlier in this same layer.
+		layer.storage.retain(|(addr, _), _| *addr != address);
+		layer.reset_storage.insert(address);
+	}
+
+	/// Record the deletion of `address`.
+	pub fn set_deleted(&mut self, address: H160) {
+		let layer = self.layers.last_mut().expect("overlay always has a layer; qed");
+		// The deletion supersedes writes buffered earlier in this same layer.
+		layer.basics.remove(&address);
+		layer.codes.remove(&address);
+		layer.storage.retain(|(addr, _), _| *addr != address);
+		layer.reset_storage.remove(&address);
+		layer.deleted.insert(address);
+	}
+
+	/// Append a log to the topmost layer.
+	pub fn log(&mut self, log: Log) {
+		self.layers.last_mut().expect("overlay always has a layer; qed")
+			.logs.push(log);
+	}
+
+	/// Flatten every live layer into the ordered change set it represents.
+	fn flatten(&self) -> Layer {
+		let mut acc = Layer::default();
+		for layer in &self.layers {
+			acc.absorb(layer.clone());
+		}
+		acc
+	}
+
+	/// Consume the overlay, returning the buffered changes as an [`Apply`]
+	/// iterator together with the accumulated logs.
+	pub fn into_apply(self) -> Result<(Vec<Apply<Vec<(H256, H256)>>>, Vec<Log>), B::Error> {
+		let flat = self.flatten();
+
+		let mut addresses = BTreeSet::new();
+		addresses.extend(flat.basics.keys().copied());
+		addresses.extend(flat.codes.keys().copied());
+		addresses.extend(flat.storage.keys().map(|(address, _)| *address));
+		addresses.extend(flat.reset_storage.iter().copied());
+		addresses.extend(flat.deleted.iter().copied());
+
+		let mut values = Vec::with_capacity(addresses.len());
+		for address in addresses {
+			if flat.deleted.contains(&address) {
+				values.push(Apply::Delete { address });
+				continue;
+			}
+
+			let storage = flat.storage.iter()
+				.filter(|((addr, _), _)| *addr == address)
+				.map(|((_, index), value)| (*index, *value))
+				.collect();
+
+			// Fall back to the wrapped backend when no basic was buffered, so a
+			// storage- or code-only write does not reset the account's balance
+			// and nonce to zero.
+			let basic = match flat.basics.get(&address).cloned() {
+				Some(basic) => basic,
+				None => self.backend.basic(address)?,
+			};
+
+			values.push(Apply::Modify {
+				address,
+				basic,
+				code: flat.codes.get(&address).cloned(),
+				storage,
+				reset_storage: flat.reset_storage.contains(&address),
+			});
+		}
+
+		Ok((values, flat.logs))
+	}
+}
+
+impl<'backend, B: Backend> Backend for OverlayBackend<'backend, B> {
+	type Error = B::Error;
+
+	fn gas_price(&self) -> U256 { self.backend.gas_price() }
+	fn origin(&self) -> H160 { self.backend.origin() }
+	fn block_number(&self) -> U256 { self.backend.block_number() }
+	fn block_coinbase(&self) -> H160 { self.backend.block_coinbase() }
+	fn block_timestamp(&self) -> U256 { self.backend.block_timestamp() }
+	fn block_difficulty(&self) -> U256 { self.backend.block_difficulty() }
+	fn block_gas_limit(&self) -> U256 { self.backend.block_gas_limit() }
+	fn chain_id(&self) -> U256 { self.backend.chain_id() }
+	fn block_base_fee_per_gas(&self) -> U256 { self.backend.block_base_fee_per_gas() }
+
+	fn block_hash(&self, number: U256) -> Result<H256, Self::Error> {
+		self.backend.block_hash(number)
+	}
+
+	fn exists(&self, address: H160) -> Result<bool, Self::Error> {
+		if self.is_deleted(address) {
+			return Ok(false);
+		}
+		if self.top(|layer| layer.basics.get(&address)).is_some() {
+			return Ok(true);
+		}
+		self.backend.exists(address)
+	}
+
+	fn basic(&self, address: H160) -> Result<Basic, Self::Error> {
+		if self.is_deleted(address) {
+			return Ok(Basic::default());
+		}
+		match self.top(|layer| layer.basics.get(&address).cloned()) {
+			Some(basic) => Ok(basic),
+			None => self.backend.basic(address),
+		}
+	}
+
+	fn code(&self, address: H160) -> Result<Vec<u8>, Self::Error> {
+		if self.is_deleted(address) {
+			return Ok(Vec::new());
+		}
+		match self.top(|layer| layer.codes.get(&address).cloned()) {
+			Some(code) => Ok(code),
+			None => self.backend.code(address),
+		}
+	}
+
+	fn code_hash(&self, address: H160) -> Result<H256, Self::Error> {
+		use sha3::{Digest, Keccak256};
+		if self.is_deleted(address) {
+			return Ok(H256::from_slice(Keccak256::digest([]).as_slice()));
+		}
+		if let Some(code) = self.top(|layer| layer.codes.get(&address).cloned()) {
+			return Ok(H256::from_slice(Keccak256::digest(&code).as_slice()));
+		}
+		self.backend.code_hash(address)
+	}
+
+	fn code_size(&self, address: H160) -> Result<usize, Self::Error> {
+		if self.is_deleted(address) {
+			return Ok(0);
+		}
+		if let Some(code) = self.top(|layer| layer.codes.get(&address).cloned()) {
+			return Ok(code.len());
+		}
+		self.backend.code_size(address)
+	}
+
+	fn storage(&self, address: H160, index: H256) -> Result<H256, Self::Error> {
+		// Walk layers top-down: a write wins over anything below it, but a reset
+		// recorded in a higher layer masks slots written (or wrapped) below it.
+		for layer in self.layers.iter().rev() {
+			if let Some(value) = layer.storage.get(&(address, index)) {
+				return Ok(*value);
+			}
+			if layer.reset_storage.contains(&address) {
+				return Ok(H256::default());
+			}
+		}
+		self.backend.storage(address, index)
+	}
+
+	fn handle_call(
+		&self,
+		code_address: H160,
+		transfer: Option<Transfer>,
+		input: Vec<u8>,
+		target_gas: Option<usize>,
+		is_static: bool,
+		take_l64: bool,
+		take_stipend: bool,
+		context: Context,
+	) -> Option<Capture<(ExitReason, Vec<u8>), Infallible>> {
+		self.backend.handle_call(
+			code_address, transfer, input, target_gas,
+			is_static, take_l64, take_stipend, context,
+		)
+	}
+}
@@ -0,0 +1,63 @@
+//! State-root computation for [`MemoryBackend`].
+//!
+//! Builds the Merkle-Patricia root of the in-memory account map so that
+//! `ApplyBackend` results can be checked against the roots found in block
+//! headers and the JSON test suite.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use primitive_types::{H160, H256, U256};
+use sha3::{Digest, Keccak256};
+use crate::backend::MemoryBackend;
+
+/// `keccak256` of `data`.
+fn keccak256(data: &[u8]) -> H256 {
+	H256::from_slice(Keccak256::digest(data).as_slice())
+}
+
+/// RLP-encode a 32-byte word with its leading zero bytes trimmed, matching the
+/// encoding used for trie storage values.
+fn trimmed_rlp(value: H256) -> Vec<u8> {
+	let word = U256::from_big_endian(value.as_bytes());
+	let mut bytes = [0u8; 32];
+	word.to_big_endian(&mut bytes);
+	let start = bytes.iter().position(|b| *b != 0).unwrap_or(32);
+	rlp::encode(&&bytes[start..]).to_vec()
+}
+
+impl<'vicinity> MemoryBackend<'vicinity> {
+	/// Merkle-Patricia state root over the current account map.
+	///
+	/// Each account is encoded as `[nonce, balance, storage_root, code_hash]`
+	/// and inserted into the outer trie under `keccak256(address)`.
+	pub fn state_root(&self) -> H256 {
+		let storage_roots = self.storage_roots();
+
+		let input = self.state().iter().map(|(address, account)| {
+			let mut stream = rlp::RlpStream::new_list(4);
+			stream.append(&account.nonce);
+			stream.append(&account.balance);
+			stream.append(&storage_roots[address]);
+			stream.append(&keccak256(&account.code));
+
+			(keccak256(address.as_bytes()), stream.out().to_vec())
+		}).collect::<Vec<_>>();
+
+		H256(triehash::trie_root::<keccak_hasher::KeccakHasher, _, _, _>(input).0)
+	}
+
+	/// Per-account storage trie roots, keyed by account address.
+	///
+	/// Each storage slot is inserted under `keccak256(index)` with the trimmed
+	/// RLP of its 32-byte word as the value.
+	pub fn storage_roots(&self) -> BTreeMap<H160, H256> {
+		self.state().iter().map(|(address, account)| {
+			let input = account.storage.iter().map(|(index, value)| {
+				(keccak256(index.as_bytes()), trimmed_rlp(*value))
+			}).collect::<Vec<_>>();
+
+			let root = H256(triehash::trie_root::<keccak_hasher::KeccakHasher, _, _, _>(input).0);
+			(*address, root)
+		}).collect()
+	}
+}